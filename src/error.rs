@@ -0,0 +1,57 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// Errors that can occur while producing a daily feed
+#[derive(Debug)]
+pub enum DailyFeedError {
+    /// the HTTP client could not be built
+    Client(reqwest::Error),
+    /// the HTTP request for a feed failed
+    Fetch(reqwest::Error),
+    /// the fetched content was not a valid RSS channel
+    Parse(rss::Error),
+    /// the feed kept redirecting past the redirect limit
+    TooManyRedirects(reqwest::Url),
+    /// the run did not finish within its deadline
+    Timeout(Duration),
+}
+
+impl fmt::Display for DailyFeedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DailyFeedError::Client(err) => write!(f, "failed to build HTTP client: {err}"),
+            DailyFeedError::Fetch(err) => write!(f, "failed to fetch feed: {err}"),
+            DailyFeedError::Parse(err) => write!(f, "failed to parse feed: {err}"),
+            DailyFeedError::TooManyRedirects(url) => {
+                write!(f, "too many redirects fetching feed, last at {url}")
+            }
+            DailyFeedError::Timeout(deadline) => {
+                write!(f, "run did not finish within its {deadline:?} deadline")
+            }
+        }
+    }
+}
+
+impl Error for DailyFeedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DailyFeedError::Client(err) => Some(err),
+            DailyFeedError::Fetch(err) => Some(err),
+            DailyFeedError::Parse(err) => Some(err),
+            DailyFeedError::TooManyRedirects(_) | DailyFeedError::Timeout(_) => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for DailyFeedError {
+    fn from(err: reqwest::Error) -> Self {
+        DailyFeedError::Fetch(err)
+    }
+}
+
+impl From<rss::Error> for DailyFeedError {
+    fn from(err: rss::Error) -> Self {
+        DailyFeedError::Parse(err)
+    }
+}
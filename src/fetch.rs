@@ -1,7 +1,395 @@
-use std::error::Error;
+use futures::StreamExt;
+use reqwest::header::LOCATION;
+use reqwest::StatusCode;
+use std::future::Future;
+use std::time::Duration;
 
-pub async fn feed_from_url(url: &str) -> Result<rss::Channel, Box<dyn Error>> {
-    let content = reqwest::get(url).await?.bytes().await?;
-    let channel = rss::Channel::read_from(&content[..])?;
-    Ok(channel)
+use crate::error::DailyFeedError;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_REDIRECTS: usize = 5;
+
+/// A fetched channel along with the URL it was finally served from
+pub struct FetchedFeed {
+    pub url: reqwest::Url,
+    /// where the feed has permanently moved to, if the redirect chain started
+    /// with permanent (301 or 308) hops; the target of the last such hop
+    pub canonical_url: Option<reqwest::Url>,
+    pub channel: rss::Channel,
+}
+
+/// Fetches feeds over a single shared, configured HTTP client
+pub struct FeedFetcher {
+    client: reqwest::Client,
+}
+
+impl FeedFetcher {
+    pub fn new() -> Result<Self, DailyFeedError> {
+        Self::with_timeout(DEFAULT_TIMEOUT)
+    }
+
+    pub fn with_timeout(timeout: Duration) -> Result<Self, DailyFeedError> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(concat!(
+                env!("CARGO_PKG_NAME"),
+                "/",
+                env!("CARGO_PKG_VERSION")
+            ))
+            .build()
+            .map_err(DailyFeedError::Client)?;
+        Ok(Self { client })
+    }
+
+    pub async fn feed_from_url(&self, url: &str) -> Result<FetchedFeed, DailyFeedError> {
+        // Redirects are followed by hand so each hop's status can be inspected
+        let mut response = self.client.get(url).send().await?;
+        let mut canonical_url = None;
+        let mut only_permanent_hops = true;
+        for _ in 0..MAX_REDIRECTS {
+            let Some(next) = redirect_target(&response) else {
+                break;
+            };
+            only_permanent_hops &= matches!(
+                response.status(),
+                StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT
+            );
+            if only_permanent_hops {
+                canonical_url = Some(next.clone());
+            }
+            response = self.client.get(next).send().await?;
+        }
+        if redirect_target(&response).is_some() {
+            return Err(DailyFeedError::TooManyRedirects(response.url().clone()));
+        }
+
+        let final_url = response.url().clone();
+        let content = response.error_for_status()?.bytes().await?;
+        let channel = rss::Channel::read_from(&content[..])?;
+        Ok(FetchedFeed {
+            url: final_url,
+            canonical_url,
+            channel,
+        })
+    }
+}
+
+/// Fetches every URL with `fetch_one`, at most `max_concurrent` at a time, returning
+/// each URL's result in URL order; only passing `deadline` fails the whole call
+pub async fn fetch_all<'a, T, F, Fut>(
+    urls: &'a [String],
+    max_concurrent: usize,
+    deadline: Option<Duration>,
+    fetch_one: F,
+) -> Result<Vec<Result<T, DailyFeedError>>, DailyFeedError>
+where
+    F: FnMut(&'a str) -> Fut,
+    Fut: Future<Output = Result<T, DailyFeedError>>,
+{
+    let fetches = futures::stream::iter(urls.iter().map(String::as_str).map(fetch_one))
+        .buffered(max_concurrent.max(1))
+        .collect::<Vec<_>>();
+    match deadline {
+        Some(deadline) => tokio::time::timeout(deadline, fetches)
+            .await
+            .map_err(|_| DailyFeedError::Timeout(deadline)),
+        None => Ok(fetches.await),
+    }
+}
+
+fn redirect_target(response: &reqwest::Response) -> Option<reqwest::Url> {
+    if !response.status().is_redirection() {
+        return None;
+    }
+    let location = response.headers().get(LOCATION)?.to_str().ok()?;
+    response.url().join(location).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const FEED: &str = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel><title>Test feed</title><link>http://example.com</link><description>A feed</description></channel></rss>"#;
+
+    fn response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+        let mut response = format!("HTTP/1.1 {status}\r\nConnection: close\r\n");
+        for (name, value) in headers {
+            response.push_str(&format!("{name}: {value}\r\n"));
+        }
+        response.push_str(&format!("Content-Length: {}\r\n\r\n{body}", body.len()));
+        response
+    }
+
+    /// Serves `route(path)` for every request on a local port, recording each request head
+    async fn serve(route: fn(&str) -> String) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    let mut head = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                        let n = stream.read(&mut buf).await.unwrap();
+                        if n == 0 {
+                            return;
+                        }
+                        head.extend_from_slice(&buf[..n]);
+                    }
+                    let head = String::from_utf8_lossy(&head).into_owned();
+                    let path = head.split(' ').nth(1).unwrap_or("/").to_string();
+                    recorded.lock().unwrap().push(head);
+                    stream.write_all(route(&path).as_bytes()).await.unwrap();
+                    stream.shutdown().await.unwrap();
+                });
+            }
+        });
+        (base, requests)
+    }
+
+    fn feed_route(_path: &str) -> String {
+        response("200 OK", &[("Content-Type", "application/rss+xml")], FEED)
+    }
+
+    fn missing_route(_path: &str) -> String {
+        response(
+            "404 Not Found",
+            &[("Content-Type", "text/html")],
+            "<html><body>Not found</body></html>",
+        )
+    }
+
+    fn redirecting_route(path: &str) -> String {
+        match path {
+            "/old.xml" => response("301 Moved Permanently", &[("Location", "/feed.xml")], ""),
+            "/tracked.xml" => response("302 Found", &[("Location", "/feed.xml")], ""),
+            "/moved-then-tracked.xml" => {
+                response("301 Moved Permanently", &[("Location", "/tracked.xml")], "")
+            }
+            "/tracked-then-moved.xml" => response("302 Found", &[("Location", "/old.xml")], ""),
+            "/loop.xml" => response("307 Temporary Redirect", &[("Location", "/loop.xml")], ""),
+            _ => feed_route(path),
+        }
+    }
+
+    #[tokio::test]
+    async fn fetches_send_the_configured_user_agent() {
+        let (base, requests) = serve(feed_route).await;
+        let fetcher = FeedFetcher::new().unwrap();
+
+        let first = fetcher
+            .feed_from_url(&format!("{base}/one.xml"))
+            .await
+            .unwrap();
+        let second = fetcher
+            .feed_from_url(&format!("{base}/two.xml"))
+            .await
+            .unwrap();
+        assert_eq!(first.channel.title(), "Test feed");
+        assert_eq!(second.channel.title(), "Test feed");
+
+        let user_agent = concat!(
+            "user-agent: ",
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        );
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        for request in requests.iter() {
+            assert!(request.to_ascii_lowercase().contains(user_agent));
+        }
+    }
+
+    #[tokio::test]
+    async fn configured_timeout_applies_to_fetches() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/slow.xml", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                held.push(stream);
+            }
+        });
+        let fetcher = FeedFetcher::with_timeout(Duration::from_secs(1)).unwrap();
+
+        match fetcher.feed_from_url(&url).await {
+            Err(DailyFeedError::Fetch(err)) => assert!(err.is_timeout()),
+            Err(err) => panic!("expected a fetch timeout, got {err}"),
+            Ok(_) => panic!("expected a fetch timeout"),
+        }
+    }
+
+    #[tokio::test]
+    async fn permanent_redirect_is_followed_and_recorded() {
+        let (base, _) = serve(redirecting_route).await;
+        let fetcher = FeedFetcher::new().unwrap();
+
+        let feed = fetcher
+            .feed_from_url(&format!("{base}/old.xml"))
+            .await
+            .unwrap();
+        assert_eq!(feed.channel.title(), "Test feed");
+        assert_eq!(feed.url.as_str(), format!("{base}/feed.xml"));
+        assert_eq!(feed.canonical_url, Some(feed.url.clone()));
+    }
+
+    #[tokio::test]
+    async fn temporary_redirect_is_not_permanent() {
+        let (base, _) = serve(redirecting_route).await;
+        let fetcher = FeedFetcher::new().unwrap();
+
+        let feed = fetcher
+            .feed_from_url(&format!("{base}/tracked.xml"))
+            .await
+            .unwrap();
+        assert_eq!(feed.url.as_str(), format!("{base}/feed.xml"));
+        assert_eq!(feed.canonical_url, None);
+    }
+
+    #[tokio::test]
+    async fn canonical_url_stops_at_the_first_temporary_hop() {
+        let (base, _) = serve(redirecting_route).await;
+        let fetcher = FeedFetcher::new().unwrap();
+
+        let feed = fetcher
+            .feed_from_url(&format!("{base}/moved-then-tracked.xml"))
+            .await
+            .unwrap();
+        assert_eq!(feed.url.as_str(), format!("{base}/feed.xml"));
+        assert_eq!(
+            feed.canonical_url.as_ref().map(reqwest::Url::as_str),
+            Some(format!("{base}/tracked.xml").as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn permanent_hop_after_a_temporary_one_is_not_canonical() {
+        let (base, _) = serve(redirecting_route).await;
+        let fetcher = FeedFetcher::new().unwrap();
+
+        let feed = fetcher
+            .feed_from_url(&format!("{base}/tracked-then-moved.xml"))
+            .await
+            .unwrap();
+        assert_eq!(feed.url.as_str(), format!("{base}/feed.xml"));
+        assert_eq!(feed.canonical_url, None);
+    }
+
+    #[tokio::test]
+    async fn redirect_loop_is_capped() {
+        let (base, requests) = serve(redirecting_route).await;
+        let fetcher = FeedFetcher::new().unwrap();
+
+        let result = fetcher.feed_from_url(&format!("{base}/loop.xml")).await;
+        assert!(matches!(result, Err(DailyFeedError::TooManyRedirects(_))));
+        assert_eq!(requests.lock().unwrap().len(), MAX_REDIRECTS + 1);
+    }
+
+    #[tokio::test]
+    async fn fetch_all_stops_at_the_deadline() {
+        let urls = vec!["slow".to_string()];
+        let started = std::time::Instant::now();
+
+        let result = fetch_all(&urls, 1, Some(Duration::from_millis(50)), |_| async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok::<_, DailyFeedError>(())
+        })
+        .await;
+        assert!(matches!(result, Err(DailyFeedError::Timeout(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn fetch_all_keeps_url_order() {
+        let urls = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+
+        let fetched = fetch_all(&urls, 3, None, |url| async move {
+            Ok::<_, DailyFeedError>(url.to_uppercase())
+        })
+        .await
+        .unwrap();
+        let fetched: Vec<_> = fetched.into_iter().map(Result::unwrap).collect();
+        assert_eq!(fetched, ["A", "B", "C"]);
+    }
+
+    #[tokio::test]
+    async fn connection_failure_is_a_fetch_error() {
+        let fetcher = FeedFetcher::new().unwrap();
+
+        let result = fetcher.feed_from_url("http://127.0.0.1:1/").await;
+        assert!(matches!(result, Err(DailyFeedError::Fetch(_))));
+    }
+
+    #[tokio::test]
+    async fn error_status_is_a_fetch_error() {
+        let (base, _) = serve(missing_route).await;
+        let fetcher = FeedFetcher::new().unwrap();
+
+        let result = fetcher.feed_from_url(&format!("{base}/gone.xml")).await;
+        match result {
+            Err(DailyFeedError::Fetch(err)) => {
+                assert_eq!(err.status(), Some(StatusCode::NOT_FOUND))
+            }
+            Err(err) => panic!("expected a fetch error, got {err}"),
+            Ok(_) => panic!("expected a fetch error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_all_never_exceeds_max_concurrent() {
+        let urls: Vec<String> = (0..12).map(|i| format!("feed-{i}")).collect();
+        let in_flight = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        let fetched = fetch_all(&urls, 3, None, |url| {
+            let (in_flight, peak) = (&in_flight, &peak);
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                Ok::<_, DailyFeedError>(url)
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(fetched.len(), urls.len());
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn fetch_all_keeps_going_after_a_failed_fetch() {
+        let urls = vec!["a".to_string(), "bad".to_string(), "c".to_string()];
+
+        let fetched = fetch_all(&urls, 3, None, |url| async move {
+            if url == "bad" {
+                Err(DailyFeedError::TooManyRedirects(
+                    reqwest::Url::parse("http://example.com/bad").unwrap(),
+                ))
+            } else {
+                Ok(url.to_uppercase())
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(fetched.len(), 3);
+        assert_eq!(fetched[0].as_deref().ok(), Some("A"));
+        assert!(matches!(
+            fetched[1],
+            Err(DailyFeedError::TooManyRedirects(_))
+        ));
+        assert_eq!(fetched[2].as_deref().ok(), Some("C"));
+    }
 }
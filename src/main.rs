@@ -1,6 +1,12 @@
 use clap::Parser;
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::process::ExitCode;
+use std::time::Duration;
+mod error;
 mod fetch;
 
+const DEFAULT_FEED_URL: &str = "https://feeds.arstechnica.com/arstechnica/index";
+
 #[derive(Parser, Debug)]
 #[clap(author = "Simon Zeng", version, about)]
 /// Application configuration
@@ -12,17 +18,126 @@ struct Args {
     /// an optional name to green
     #[arg()]
     name: Option<String>,
+
+    /// seconds to wait on each feed request before giving up
+    #[arg(long, value_name = "SECS")]
+    fetch_timeout: Option<NonZeroU64>,
+
+    /// feed to fetch; repeat to fetch several
+    #[arg(long = "feed-url", value_name = "URL", default_value = DEFAULT_FEED_URL)]
+    feed_urls: Vec<String>,
+
+    /// seconds the whole run may take before it is aborted
+    #[arg(long, value_name = "SECS")]
+    deadline: Option<NonZeroU64>,
+
+    /// maximum number of feeds to fetch at once (default: all of them)
+    #[arg(long, value_name = "N")]
+    max_concurrent_feeds: Option<NonZeroUsize>,
+
+    /// only fetch the first N feeds
+    #[arg(long, value_name = "N")]
+    limit_feeds: Option<NonZeroUsize>,
+}
+
+/// The configured feeds, cut down to `--limit-feeds` if given
+fn feeds_to_fetch(args: &Args) -> &[String] {
+    match args.limit_feeds {
+        Some(limit) => &args.feed_urls[..args.feed_urls.len().min(limit.get())],
+        None => &args.feed_urls,
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+async fn main() -> ExitCode {
+    match run(Args::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<(), error::DailyFeedError> {
     if args.verbose {
         println!("DEBUG {args:?}");
     }
-    println!("Hello {}!", args.name.unwrap_or("world".to_string()));
-    let feed_result =
-        fetch::feed_from_url("https://feeds.arstechnica.com/arstechnica/index").await?;
-    println!("{:?}", feed_result);
+    println!("Hello {}!", args.name.as_deref().unwrap_or("world"));
+    let feed_urls = feeds_to_fetch(&args);
+    let fetcher = match args.fetch_timeout {
+        Some(secs) => fetch::FeedFetcher::with_timeout(Duration::from_secs(secs.get()))?,
+        None => fetch::FeedFetcher::new()?,
+    };
+    let max_concurrent_feeds = args
+        .max_concurrent_feeds
+        .map_or(feed_urls.len(), NonZeroUsize::get);
+    let feed_results = fetch::fetch_all(
+        feed_urls,
+        max_concurrent_feeds,
+        args.deadline.map(|secs| Duration::from_secs(secs.get())),
+        |url| fetcher.feed_from_url(url),
+    )
+    .await?;
+    for (requested_url, feed_result) in feed_urls.iter().zip(feed_results) {
+        let feed_result = match feed_result {
+            Ok(feed_result) => feed_result,
+            Err(err) => {
+                eprintln!("warning: skipping {requested_url}: {err}");
+                continue;
+            }
+        };
+        if args.verbose {
+            println!("DEBUG fetched {requested_url} from {}", feed_result.url);
+        }
+        if let Some(canonical_url) = &feed_result.canonical_url {
+            eprintln!(
+                "note: {requested_url} permanently redirected to {canonical_url}, consider using that URL instead"
+            );
+        }
+        println!("{:?}", feed_result.channel);
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feed_url_defaults_to_ars() {
+        let args = Args::try_parse_from(["daily-feed"]).unwrap();
+        assert_eq!(args.feed_urls, [DEFAULT_FEED_URL]);
+    }
+
+    #[test]
+    fn feed_url_replaces_the_default() {
+        let args =
+            Args::try_parse_from(["daily-feed", "--feed-url", "http://example.com/feed.xml"])
+                .unwrap();
+        assert_eq!(args.feed_urls, ["http://example.com/feed.xml"]);
+    }
+
+    #[test]
+    fn limit_feeds_keeps_only_the_first_feeds() {
+        let args = Args::try_parse_from([
+            "daily-feed",
+            "--feed-url",
+            "http://example.com/one.xml",
+            "--feed-url",
+            "http://example.com/two.xml",
+            "--feed-url",
+            "http://example.com/three.xml",
+            "--limit-feeds",
+            "1",
+        ])
+        .unwrap();
+        assert_eq!(feeds_to_fetch(&args), ["http://example.com/one.xml"]);
+    }
+
+    #[test]
+    fn limit_feeds_above_the_feed_count_keeps_all() {
+        let args = Args::try_parse_from(["daily-feed", "--limit-feeds", "5"]).unwrap();
+        assert_eq!(feeds_to_fetch(&args), [DEFAULT_FEED_URL]);
+    }
+}